@@ -9,4 +9,24 @@ pub struct Args {
     pub source: String,
     #[arg(help = "Destination directory")]
     pub destination: PathBuf,
+    #[arg(long, help = "Keep polling a live media playlist for new segments instead of exiting after one pass")]
+    pub live: bool,
+    #[arg(long, help = "Skip variants whose RESOLUTION height is above this many pixels (variants without RESOLUTION are kept)")]
+    pub max_height: Option<u32>,
+    #[arg(long, help = "Skip variants whose BANDWIDTH is above this value (variants without BANDWIDTH are kept)")]
+    pub bandwidth_at_most: Option<u64>,
+    #[arg(long, help = "Select the variant at this zero-based index in the master playlist, bypassing other filters")]
+    pub variant_index: Option<usize>,
+    #[arg(long, conflicts_with = "worst", help = "Select the highest-bandwidth matching variant (default)")]
+    pub best: bool,
+    #[arg(long, conflicts_with = "best", help = "Select the lowest-bandwidth matching variant")]
+    pub worst: bool,
+    #[arg(long, help = "Decrypt #EXT-X-KEY AES-128 segments and save them as plaintext")]
+    pub decrypt: bool,
+    #[arg(long, default_value_t = 6, help = "Maximum number of segments to download concurrently")]
+    pub concurrency: usize,
+    #[arg(long, default_value_t = 5, help = "Maximum attempts for a playlist or segment fetch before giving up")]
+    pub retries: u32,
+    #[arg(long, help = "Ignore any existing resume manifest in the destination directory and re-download everything")]
+    pub force: bool,
 }
\ No newline at end of file
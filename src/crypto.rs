@@ -0,0 +1,42 @@
+use aes::Aes128;
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+
+use crate::error::HlsError;
+
+type Aes128CbcDec = cbc::Decryptor<Aes128>;
+
+pub fn decrypt_aes128_cbc(data: &[u8], key: &[u8; 16], iv: &[u8; 16]) -> Result<Vec<u8>, HlsError> {
+    Aes128CbcDec::new(key.into(), iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(data)
+        .map_err(|_| HlsError::DownloadError("failed to decrypt AES-128 segment (bad key, IV, or padding)".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cbc::cipher::BlockEncryptMut;
+
+    type Aes128CbcEnc = cbc::Encryptor<Aes128>;
+
+    #[test]
+    fn round_trips_through_encrypt_and_decrypt() {
+        let key = *b"0123456789abcdef";
+        let iv = *b"fedcba9876543210";
+        let plaintext = b"hls segment payload spanning more than one 16-byte block";
+
+        let ciphertext = Aes128CbcEnc::new(&key.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(plaintext);
+
+        let decrypted = decrypt_aes128_cbc(&ciphertext, &key, &iv).expect("decryption should succeed");
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn rejects_ciphertext_not_a_multiple_of_the_block_size() {
+        let key = [0u8; 16];
+        let iv = [0u8; 16];
+        let bad_ciphertext = [0u8; 5];
+
+        assert!(decrypt_aes128_cbc(&bad_ciphertext, &key, &iv).is_err());
+    }
+}
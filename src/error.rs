@@ -22,4 +22,13 @@ pub enum HlsError {
     
     #[error("Download error: {0}")]
     DownloadError(String),
+
+    #[error("Variant selection error: {0}")]
+    VariantSelectionError(String),
+
+    #[error("Retry exhausted: {0}")]
+    RetryExhaustedError(String),
+
+    #[error("Resume manifest error: {0}")]
+    ManifestError(String),
 }
\ No newline at end of file
@@ -1,37 +1,150 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use futures::future::join_all;
+use futures::{FutureExt, StreamExt};
 use indicatif::{ProgressBar, ProgressStyle, MultiProgress};
-use reqwest::Client;
+use rand::Rng;
+use reqwest::{Client, Response};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{Notify, Semaphore};
 use url::Url;
 
-use crate::playlist::Playlist;
+use crate::cli::Args;
+use crate::crypto;
+use crate::error::HlsError;
+use crate::manifest::{Manifest, SegmentHasher};
+use crate::playlist::{KeyTag, Playlist, VariantStream};
+
+const MAX_EMPTY_POLLS: u32 = 12;
+const DEFAULT_POLL_SECS: u64 = 6;
+const SEGMENT_WRITE_BUFFER_BYTES: usize = 128 * 1024;
+const RETRY_BASE_DELAY_MS: u64 = 250;
+const RETRY_MAX_DELAY_MS: u64 = 10_000;
 
 pub struct HlsCopier {
     client: Client,
     base_url: Url,
     dest_dir: PathBuf,
     multi_progress: Arc<MultiProgress>,
+    live: bool,
+    max_height: Option<u32>,
+    bandwidth_at_most: Option<u64>,
+    variant_index: Option<usize>,
+    worst: bool,
+    decrypt: bool,
+    semaphore: Arc<Semaphore>,
+    retries: u32,
+    force: bool,
 }
 
 impl HlsCopier {
-    pub fn new(source_url: &str, dest_dir: PathBuf) -> Result<Self> {
-        let base_url = Url::parse(source_url).context("Invalid source URL")?;
+    pub fn new(args: &Args) -> Result<Self> {
+        if args.live && args.decrypt {
+            bail!("--decrypt is not supported together with --live");
+        }
+
+        let base_url = Url::parse(&args.source).context("Invalid source URL")?;
         let client = Client::new();
         let multi_progress = Arc::new(MultiProgress::new());
-        
+        let semaphore = Arc::new(Semaphore::new(args.concurrency.max(1)));
+
         Ok(HlsCopier {
             client,
             base_url,
-            dest_dir,
+            dest_dir: args.destination.clone(),
             multi_progress,
+            live: args.live,
+            max_height: args.max_height,
+            bandwidth_at_most: args.bandwidth_at_most,
+            variant_index: args.variant_index,
+            worst: args.worst,
+            decrypt: args.decrypt,
+            semaphore,
+            retries: args.retries,
+            force: args.force,
         })
     }
 
+    async fn get_with_retry(&self, url: &Url, pb: &ProgressBar, what: &str) -> Result<Response> {
+        let mut attempt: u32 = 0;
+
+        loop {
+            match self.client.get(url.as_str()).send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let retryable = status.is_server_error() || status.as_u16() == 429;
+                    if !retryable || attempt + 1 >= self.retries {
+                        return Err(HlsError::RetryExhaustedError(format!("{} failed with status {}", what, status)).into());
+                    }
+                    let delay = Self::retry_after_delay(&response).unwrap_or_else(|| Self::backoff_delay(attempt));
+                    attempt += 1;
+                    pb.set_message(format!("{} (attempt {}/{}, status {})", what, attempt + 1, self.retries, status));
+                    tokio::time::sleep(delay).await;
+                }
+                Err(err) if (err.is_timeout() || err.is_connect()) && attempt + 1 < self.retries => {
+                    let delay = Self::backoff_delay(attempt);
+                    attempt += 1;
+                    pb.set_message(format!("{} (attempt {}/{}, {})", what, attempt + 1, self.retries, err));
+                    tokio::time::sleep(delay).await;
+                }
+                Err(err) => return Err(err).with_context(|| format!("{} failed", what)),
+            }
+        }
+    }
+
+    fn retry_after_delay(response: &Response) -> Option<Duration> {
+        response.headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+    }
+
+    fn backoff_delay(attempt: u32) -> Duration {
+        let exponential = RETRY_BASE_DELAY_MS.saturating_mul(1u64 << attempt.min(6));
+        let capped = exponential.min(RETRY_MAX_DELAY_MS);
+        let jitter = rand::thread_rng().gen_range(0..=capped / 4 + 1);
+        Duration::from_millis(capped + jitter)
+    }
+
+    fn select_variant<'a>(&self, variants: &'a [VariantStream]) -> Result<&'a VariantStream> {
+        if let Some(index) = self.variant_index {
+            return variants.get(index).ok_or_else(|| {
+                HlsError::VariantSelectionError(format!(
+                    "--variant-index {} is out of range ({} variants available)", index, variants.len()
+                )).into()
+            });
+        }
+
+        let mut candidates: Vec<&VariantStream> = variants.iter().collect();
+
+        if let Some(max_height) = self.max_height {
+            candidates.retain(|v| v.resolution.map(|(_, height)| height <= max_height).unwrap_or(true));
+        }
+        if let Some(max_bandwidth) = self.bandwidth_at_most {
+            candidates.retain(|v| v.bandwidth.map(|b| b <= max_bandwidth).unwrap_or(true));
+        }
+        if candidates.is_empty() {
+            return Err(HlsError::VariantSelectionError(
+                "No variant matches the requested --max-height/--bandwidth-at-most filters".to_string()
+            ).into());
+        }
+
+        if self.worst {
+            candidates.sort_by_key(|v| v.bandwidth.unwrap_or(0));
+        } else {
+            candidates.sort_by_key(|v| std::cmp::Reverse(v.bandwidth.unwrap_or(0)));
+        }
+
+        Ok(candidates[0])
+    }
+
     async fn fetch_playlist(&self, url: &Url) -> Result<String> {
         let pb = self.multi_progress.add(ProgressBar::new_spinner());
         pb.set_style(ProgressStyle::default_spinner()
@@ -39,13 +152,9 @@ impl HlsCopier {
             .unwrap());
         pb.set_message(url.as_str().to_string());
         pb.enable_steady_tick(std::time::Duration::from_millis(100));
-        
-        let response = self.client
-            .get(url.as_str())
-            .send()
-            .await
-            .context("Failed to fetch playlist")?;
-        
+
+        let response = self.get_with_retry(url, &pb, "Fetching playlist").await?;
+
         let content = response
             .text()
             .await
@@ -63,103 +172,448 @@ impl HlsCopier {
         }
     }
 
-    async fn download_segment(&self, url: &Url, filename: &str, pb: &ProgressBar) -> Result<()> {
+    async fn download_segment(
+        &self,
+        url: &Url,
+        filename: &str,
+        pb: &ProgressBar,
+        decrypt_with: Option<([u8; 16], [u8; 16])>,
+    ) -> Result<(u64, String)> {
+        let _permit = self.semaphore.acquire().await.context("Download semaphore closed unexpectedly")?;
         pb.set_message(format!("Downloading: {}", filename));
-        
-        let response = self.client
-            .get(url.as_str())
-            .send()
-            .await
-            .context("Failed to fetch segment")?;
-        
-        let bytes = response
-            .bytes()
-            .await
-            .context("Failed to read segment bytes")?;
-        
+
+        let response = self.get_with_retry(url, pb, &format!("Downloading segment {}", filename)).await?;
+
         let file_path = self.dest_dir.join(filename);
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent).context("Failed to create directory")?;
         }
-        
-        let mut file = File::create(&file_path)
-            .await
-            .with_context(|| format!("Failed to create file: {}", file_path.display()))?;
-        
-        file.write_all(&bytes)
-            .await
-            .context("Failed to write segment to file")?;
-        
+
+        let outcome = if let Some((key, iv)) = decrypt_with {
+            let bytes = response.bytes().await.context("Failed to read segment bytes")?;
+            let data = crypto::decrypt_aes128_cbc(&bytes, &key, &iv)
+                .with_context(|| format!("Failed to decrypt segment: {}", filename))?;
+
+            let mut file = File::create(&file_path)
+                .await
+                .with_context(|| format!("Failed to create file: {}", file_path.display()))?;
+            file.write_all(&data)
+                .await
+                .context("Failed to write segment to file")?;
+            file.flush().await.context("Failed to flush segment file")?;
+
+            let mut hasher = SegmentHasher::default();
+            hasher.update(&data);
+            hasher.finish()
+        } else {
+            let file = File::create(&file_path)
+                .await
+                .with_context(|| format!("Failed to create file: {}", file_path.display()))?;
+            let mut writer = BufWriter::with_capacity(SEGMENT_WRITE_BUFFER_BYTES, file);
+            let mut hasher = SegmentHasher::default();
+
+            let mut chunks = response.bytes_stream();
+            while let Some(chunk) = chunks.next().await {
+                let chunk = chunk.context("Failed to read segment chunk")?;
+                hasher.update(&chunk);
+                writer.write_all(&chunk).await.context("Failed to write segment chunk")?;
+            }
+            writer.flush().await.context("Failed to flush segment file")?;
+            hasher.finish()
+        };
+
         pb.inc(1);
+        Ok(outcome)
+    }
+
+    async fn fetch_bytes(&self, url: &Url) -> Result<Vec<u8>> {
+        let pb = self.multi_progress.add(ProgressBar::new_spinner());
+        pb.set_style(ProgressStyle::default_spinner()
+            .template("{spinner:.green} Fetching key: {msg}")
+            .unwrap());
+        pb.set_message(url.as_str().to_string());
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+
+        let response = self.get_with_retry(url, &pb, "Fetching key").await?;
+
+        let bytes = response
+            .bytes()
+            .await
+            .context("Failed to read key bytes")?;
+
+        pb.finish_with_message(format!("✓ Fetched key: {}", url.as_str()));
+        Ok(bytes.to_vec())
+    }
+
+    async fn prepare_decrypt(
+        &self,
+        key_tag: &Option<KeyTag>,
+        position: Option<u64>,
+        media_sequence: u64,
+        base_url: &Url,
+        key_cache: &mut HashMap<String, [u8; 16]>,
+    ) -> Result<Option<([u8; 16], [u8; 16])>> {
+        if !self.decrypt {
+            return Ok(None);
+        }
+        let Some(key_tag) = key_tag else { return Ok(None) };
+        if key_tag.method != "AES-128" {
+            return Ok(None);
+        }
+        let Some(key_uri) = &key_tag.uri else { return Ok(None) };
+
+        let key = match key_cache.get(key_uri) {
+            Some(cached) => *cached,
+            None => {
+                let key_url = self.resolve_url(key_uri, base_url)?;
+                let raw = self.fetch_bytes(&key_url).await?;
+                let fetched: [u8; 16] = raw.as_slice().try_into()
+                    .map_err(|_| HlsError::DownloadError(format!("AES-128 key at {} is not 16 bytes", key_url)))?;
+                key_cache.insert(key_uri.clone(), fetched);
+                fetched
+            }
+        };
+
+        let iv = key_tag.iv.unwrap_or_else(|| {
+            Playlist::derive_iv_from_sequence(media_sequence + position.unwrap_or(0))
+        });
+
+        Ok(Some((key, iv)))
+    }
+
+    async fn save_key_files(&self, playlist: &Playlist) -> Result<()> {
+        let mut seen = HashSet::new();
+        for key_tag in playlist.segment_keys.iter().flatten() {
+            if self.decrypt && key_tag.is_decryptable() {
+                continue;
+            }
+            let Some(key_uri) = &key_tag.uri else { continue };
+            if !seen.insert(key_uri.clone()) {
+                continue;
+            }
+
+            let key_url = self.resolve_url(key_uri, &playlist.url)?;
+            let filename = key_url.path_segments()
+                .and_then(|segments| segments.last())
+                .unwrap_or(key_uri)
+                .to_string();
+            let bytes = self.fetch_bytes(&key_url).await?;
+
+            let file_path = self.dest_dir.join(&filename);
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent).context("Failed to create directory")?;
+            }
+            fs::write(&file_path, &bytes)
+                .with_context(|| format!("Failed to write key: {}", file_path.display()))?;
+        }
         Ok(())
     }
 
-    async fn process_playlist(&self, playlist_url: &Url, local_filename: &str) -> Result<()> {
+    async fn process_playlist(&self, playlist_url: &Url, local_filename: &str, manifest: &tokio::sync::Mutex<Manifest>) -> Result<()> {
         let content = self.fetch_playlist(playlist_url).await?;
+
+        if self.live && !Playlist::has_endlist(&content) {
+            return self.process_live_playlist(playlist_url, local_filename, content).await;
+        }
+
         let playlist = Playlist::parse(&content, playlist_url)?;
-        
+
+        self.save_key_files(&playlist).await?;
+
         if !playlist.segments.is_empty() {
+            let media_sequence = Playlist::media_sequence(&content);
+            let mut key_cache: HashMap<String, [u8; 16]> = HashMap::new();
             let mut segment_data = Vec::new();
-            
-            for segment_url_str in &playlist.segments {
+
+            for (i, segment_url_str) in playlist.segments.iter().enumerate() {
                 let segment_url = self.resolve_url(segment_url_str, &playlist.url)?;
                 let filename = segment_url.path_segments()
                     .and_then(|segments| segments.last())
                     .unwrap_or(segment_url_str)
                     .to_string();
-                
-                segment_data.push((segment_url, filename));
-            }
-            
-            let pb = self.multi_progress.add(ProgressBar::new(segment_data.len() as u64));
-            pb.set_style(ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} segments ({msg})")
-                .unwrap()
-                .progress_chars("#>-"));
-            pb.set_message(format!("Downloading segments for {}", local_filename));
-            
-            let download_tasks = segment_data.iter()
-                .map(|(url, filename)| self.download_segment(url, filename, &pb));
-            
-            join_all(download_tasks).await.into_iter().collect::<Result<Vec<_>>>()?;
-            pb.finish_with_message(format!("✓ Downloaded {} segments for {}", segment_data.len(), local_filename));
+
+                if !self.force && manifest.lock().await.is_complete(&self.dest_dir, &filename) {
+                    continue;
+                }
+
+                let decrypt_with = self.prepare_decrypt(
+                    &playlist.segment_keys[i],
+                    playlist.segment_positions[i],
+                    media_sequence,
+                    &playlist.url,
+                    &mut key_cache,
+                ).await?;
+
+                segment_data.push((segment_url, filename, decrypt_with));
+            }
+
+            if !segment_data.is_empty() {
+                let pb = self.multi_progress.add(ProgressBar::new(segment_data.len() as u64));
+                pb.set_style(ProgressStyle::default_bar()
+                    .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} segments ({msg})")
+                    .unwrap()
+                    .progress_chars("#>-"));
+                pb.set_message(format!("Downloading segments for {}", local_filename));
+
+                let download_tasks = segment_data.iter()
+                    .map(|(url, filename, decrypt_with)| self.download_segment(url, filename, &pb, *decrypt_with));
+
+                let results = join_all(download_tasks).await;
+                let mut first_error = None;
+                let mut guard = manifest.lock().await;
+                for ((_, filename, _), result) in segment_data.iter().zip(results) {
+                    match result {
+                        Ok((size, sha256)) => guard.record_segment(filename.clone(), size, sha256),
+                        Err(err) => {
+                            if first_error.is_none() {
+                                first_error = Some(err);
+                            }
+                        }
+                    }
+                }
+                guard.save(&self.dest_dir)?;
+                drop(guard);
+
+                if let Some(err) = first_error {
+                    return Err(err);
+                }
+
+                pb.finish_with_message(format!("✓ Downloaded {} segments for {}", segment_data.len(), local_filename));
+            }
         }
-        
-        let rewritten_content = playlist.rewrite_content();
+
+        let rewritten_content = playlist.rewrite_content(self.decrypt);
         let playlist_path = self.dest_dir.join(local_filename);
-        
+
         fs::write(&playlist_path, rewritten_content)
             .with_context(|| format!("Failed to write playlist: {}", playlist_path.display()))?;
-        
+
         Ok(())
     }
 
+    async fn process_live_playlist(
+        &self,
+        playlist_url: &Url,
+        local_filename: &str,
+        mut content: String,
+    ) -> Result<()> {
+        let playlist_path = self.dest_dir.join(local_filename);
+        let header = Self::playlist_header(&content);
+
+        let pb = self.multi_progress.add(ProgressBar::new_spinner());
+        pb.set_style(ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {pos} segments captured ({msg})")
+            .unwrap());
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+
+        let interrupted = Arc::new(Notify::new());
+        {
+            let interrupted = interrupted.clone();
+            tokio::spawn(async move {
+                let _ = tokio::signal::ctrl_c().await;
+                interrupted.notify_one();
+            });
+        }
+
+        let mut seen_uris: HashSet<String> = HashSet::new();
+        let mut highest_index: i64 = -1;
+        let mut captured_lines: Vec<String> = Vec::new();
+        let mut empty_polls: u32 = 0;
+
+        loop {
+            if interrupted.notified().now_or_never().is_some() {
+                pb.finish_with_message(format!("✓ Interrupted, {} segments captured", pb.position()));
+                return Ok(());
+            }
+
+            let media_sequence = Playlist::media_sequence(&content);
+            let mut new_count = 0u64;
+
+            for (position, segment) in Playlist::media_segments(&content).into_iter().enumerate() {
+                let index = media_sequence as i64 + position as i64;
+                if index <= highest_index || !seen_uris.insert(segment.uri.clone()) {
+                    continue;
+                }
+
+                let segment_url = self.resolve_url(&segment.uri, playlist_url)?;
+                let filename = segment_url.path_segments()
+                    .and_then(|segments| segments.last())
+                    .unwrap_or(&segment.uri)
+                    .to_string();
+
+                pb.set_message(format!("Downloading: {}", filename));
+                self.download_segment(&segment_url, &filename, &pb, None).await?;
+
+                if let Some(extinf) = segment.extinf {
+                    captured_lines.push(extinf);
+                }
+                captured_lines.push(filename);
+
+                highest_index = index;
+                new_count += 1;
+            }
+
+            let finished = Playlist::has_endlist(&content);
+            let mut rewritten = header.clone();
+            rewritten.push_str(&captured_lines.join("\n"));
+            if finished {
+                rewritten.push_str("\n#EXT-X-ENDLIST\n");
+            } else {
+                rewritten.push('\n');
+            }
+            fs::write(&playlist_path, rewritten)
+                .with_context(|| format!("Failed to write playlist: {}", playlist_path.display()))?;
+
+            if finished {
+                pb.finish_with_message(format!("✓ Live capture ended, {} segments captured", pb.position()));
+                return Ok(());
+            }
+
+            if new_count == 0 {
+                empty_polls += 1;
+                if empty_polls >= MAX_EMPTY_POLLS {
+                    pb.finish_with_message(format!(
+                        "✓ No new segments after {} polls, stopping ({} segments captured)",
+                        MAX_EMPTY_POLLS, pb.position()
+                    ));
+                    return Ok(());
+                }
+            } else {
+                empty_polls = 0;
+            }
+
+            let poll_interval = Playlist::target_duration(&content).unwrap_or(DEFAULT_POLL_SECS);
+            tokio::select! {
+                _ = tokio::time::sleep(std::time::Duration::from_secs(poll_interval)) => {}
+                _ = interrupted.notified() => {
+                    pb.finish_with_message(format!("✓ Interrupted, {} segments captured", pb.position()));
+                    return Ok(());
+                }
+            }
+            content = self.fetch_playlist(playlist_url).await?;
+        }
+    }
+
+    fn playlist_header(content: &str) -> String {
+        let mut header = String::new();
+        for line in content.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("#EXTINF:") || (!trimmed.starts_with('#') && !trimmed.is_empty()) {
+                break;
+            }
+            header.push_str(trimmed);
+            header.push('\n');
+        }
+        header
+    }
+
     pub async fn copy_hls(&self) -> Result<()> {
         fs::create_dir_all(&self.dest_dir).context("Failed to create destination directory")?;
-        
+
+        let mut manifest = if self.force {
+            Manifest::new(self.base_url.to_string())
+        } else {
+            let loaded = Manifest::load_or_default(&self.dest_dir, self.base_url.to_string());
+            if loaded.source == self.base_url.as_str() {
+                loaded
+            } else {
+                Manifest::new(self.base_url.to_string())
+            }
+        };
+
         let master_filename = self.base_url.path_segments()
             .and_then(|segments| segments.last())
-            .unwrap_or("playlist.m3u8");
-        
+            .unwrap_or("playlist.m3u8")
+            .to_string();
+
         let master_content = self.fetch_playlist(&self.base_url).await?;
-        let master_path = self.dest_dir.join(master_filename);
-        fs::write(&master_path, &master_content).context("Failed to write master playlist")?;
-        
+
         if Playlist::is_master_playlist(&master_content) {
-            let stream_playlists = Playlist::extract_all_playlists(&master_content);
-            
-            for stream_playlist_url_str in stream_playlists {
-                let stream_playlist_url = self.resolve_url(&stream_playlist_url_str, &self.base_url)?;
-                let stream_filename = stream_playlist_url.path_segments()
+            let variants = Playlist::extract_variants(&master_content);
+            if variants.is_empty() {
+                return Err(HlsError::VariantSelectionError(
+                    "Master playlist has no #EXT-X-STREAM-INF variants to select from".to_string()
+                ).into());
+            }
+            let selected = self.select_variant(&variants)?.clone();
+            if manifest.variant_uri.as_deref() != Some(selected.uri.as_str()) {
+                manifest.segments.clear();
+                manifest.variant_uri = Some(selected.uri.clone());
+            }
+
+            let stream_playlist_url = self.resolve_url(&selected.uri, &self.base_url)?;
+            let stream_filename = stream_playlist_url.path_segments()
+                .and_then(|segments| segments.last())
+                .unwrap_or(&selected.uri)
+                .to_string();
+
+            let keep_groups: Vec<&str> = [selected.audio_group.as_deref(), selected.subtitles_group.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect();
+
+            let mut rendition_filenames = HashMap::new();
+            let mut renditions = Vec::new();
+            for rendition in Playlist::extract_media_renditions(&master_content) {
+                if !keep_groups.contains(&rendition.group_id.as_str()) {
+                    continue;
+                }
+                let Some(rendition_uri) = rendition.uri else { continue };
+
+                let rendition_url = self.resolve_url(&rendition_uri, &self.base_url)?;
+                let rendition_filename = rendition_url.path_segments()
                     .and_then(|segments| segments.last())
-                    .unwrap_or(&stream_playlist_url_str);
-                
-                self.process_playlist(&stream_playlist_url, stream_filename).await?;
+                    .unwrap_or(&rendition_uri)
+                    .to_string();
+
+                rendition_filenames.insert(rendition_uri, rendition_filename.clone());
+                renditions.push((rendition_url, rendition_filename));
+            }
+
+            let manifest = tokio::sync::Mutex::new(manifest);
+
+            if self.live {
+                let stream_task = self.process_playlist(&stream_playlist_url, &stream_filename, &manifest);
+                let rendition_tasks = renditions.iter()
+                    .map(|(url, filename)| self.process_playlist(url, filename, &manifest));
+                let (stream_result, rendition_results) =
+                    tokio::join!(stream_task, join_all(rendition_tasks));
+
+                let mut first_error = stream_result.err();
+                for result in rendition_results {
+                    if let Err(err) = result {
+                        if first_error.is_none() {
+                            first_error = Some(err);
+                        }
+                    }
+                }
+                if let Some(err) = first_error {
+                    return Err(err);
+                }
+            } else {
+                self.process_playlist(&stream_playlist_url, &stream_filename, &manifest).await?;
+                for (url, filename) in &renditions {
+                    self.process_playlist(url, filename, &manifest).await?;
+                }
             }
+
+            let rewritten_master = Playlist::rewrite_master_single_variant(
+                &master_content,
+                &selected,
+                &stream_filename,
+                &rendition_filenames,
+            );
+            let master_path = self.dest_dir.join(&master_filename);
+            fs::write(&master_path, rewritten_master).context("Failed to write master playlist")?;
         } else {
-            self.process_playlist(&self.base_url, master_filename).await?;
+            if manifest.variant_uri.is_some() {
+                manifest.segments.clear();
+                manifest.variant_uri = None;
+            }
+            let manifest = tokio::sync::Mutex::new(manifest);
+            self.process_playlist(&self.base_url, &master_filename, &manifest).await?;
         }
-        
+
         Ok(())
     }
 }
\ No newline at end of file
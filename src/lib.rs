@@ -1,5 +1,7 @@
 pub mod cli;
+pub mod crypto;
 pub mod error;
+pub mod manifest;
 pub mod playlist;
 pub mod hls_copier;
 
@@ -7,7 +7,7 @@ use hlscp::{Args, HlsCopier};
 async fn main() -> Result<()> {
     let args = Args::parse();
     
-    let copier = HlsCopier::new(&args.source, args.destination)?;
+    let copier = HlsCopier::new(&args)?;
     copier.copy_hls().await?;
     
     println!("✓ HLS copy completed successfully!");
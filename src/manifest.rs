@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::HlsError;
+
+pub const MANIFEST_FILENAME: &str = ".hlscp-manifest.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub source: String,
+    pub variant_uri: Option<String>,
+    pub segments: HashMap<String, SegmentRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentRecord {
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn new(source: String) -> Self {
+        Manifest { source, variant_uri: None, segments: HashMap::new() }
+    }
+
+    fn path(dest_dir: &Path) -> PathBuf {
+        dest_dir.join(MANIFEST_FILENAME)
+    }
+
+    pub fn load_or_default(dest_dir: &Path, source: String) -> Self {
+        fs::read_to_string(Self::path(dest_dir))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_else(|| Manifest::new(source))
+    }
+
+    pub fn save(&self, dest_dir: &Path) -> Result<(), HlsError> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| HlsError::ManifestError(format!("failed to serialize resume manifest: {}", e)))?;
+        fs::write(Self::path(dest_dir), content)?;
+        Ok(())
+    }
+
+    pub fn is_complete(&self, dest_dir: &Path, filename: &str) -> bool {
+        let Some(record) = self.segments.get(filename) else { return false };
+        let path = dest_dir.join(filename);
+
+        let Ok(metadata) = fs::metadata(&path) else { return false };
+        if metadata.len() != record.size {
+            return false;
+        }
+
+        let Ok(bytes) = fs::read(&path) else { return false };
+        format!("{:x}", Sha256::digest(&bytes)) == record.sha256
+    }
+
+    pub fn record_segment(&mut self, filename: String, size: u64, sha256: String) {
+        self.segments.insert(filename, SegmentRecord { size, sha256 });
+    }
+}
+
+#[derive(Default)]
+pub struct SegmentHasher {
+    hasher: Sha256,
+    size: u64,
+}
+
+impl SegmentHasher {
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.size += chunk.len() as u64;
+    }
+
+    pub fn finish(self) -> (u64, String) {
+        (self.size, format!("{:x}", self.hasher.finalize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hlscp-manifest-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn is_complete_false_when_segment_was_never_recorded() {
+        let dir = test_dir("never_recorded");
+        let manifest = Manifest::new("https://example.com/master.m3u8".to_string());
+        assert!(!manifest.is_complete(&dir, "segment-001.ts"));
+    }
+
+    #[test]
+    fn is_complete_false_when_recorded_but_file_missing() {
+        let dir = test_dir("file_missing");
+        let mut manifest = Manifest::new("https://example.com/master.m3u8".to_string());
+        manifest.record_segment("segment-001.ts".to_string(), 4, "deadbeef".to_string());
+        assert!(!manifest.is_complete(&dir, "segment-001.ts"));
+    }
+
+    #[test]
+    fn is_complete_false_when_file_size_does_not_match() {
+        let dir = test_dir("size_mismatch");
+        fs::write(dir.join("segment-001.ts"), b"ab").unwrap();
+        let mut manifest = Manifest::new("https://example.com/master.m3u8".to_string());
+        manifest.record_segment("segment-001.ts".to_string(), 4, "deadbeef".to_string());
+        assert!(!manifest.is_complete(&dir, "segment-001.ts"));
+    }
+
+    #[test]
+    fn is_complete_true_when_size_and_hash_match_the_record() {
+        let dir = test_dir("size_and_hash_match");
+        fs::write(dir.join("segment-001.ts"), b"abcd").unwrap();
+        let sha256 = format!("{:x}", Sha256::digest(b"abcd"));
+        let mut manifest = Manifest::new("https://example.com/master.m3u8".to_string());
+        manifest.record_segment("segment-001.ts".to_string(), 4, sha256);
+        assert!(manifest.is_complete(&dir, "segment-001.ts"));
+    }
+
+    #[test]
+    fn is_complete_false_when_size_matches_but_content_changed() {
+        let dir = test_dir("hash_mismatch");
+        fs::write(dir.join("segment-001.ts"), b"abcd").unwrap();
+        let mut manifest = Manifest::new("https://example.com/master.m3u8".to_string());
+        manifest.record_segment("segment-001.ts".to_string(), 4, format!("{:x}", Sha256::digest(b"wxyz")));
+        assert!(!manifest.is_complete(&dir, "segment-001.ts"));
+    }
+
+    #[test]
+    fn save_and_load_round_trip_preserves_segments() {
+        let dir = test_dir("round_trip");
+        let mut manifest = Manifest::new("https://example.com/master.m3u8".to_string());
+        manifest.variant_uri = Some("variant.m3u8".to_string());
+        manifest.record_segment("segment-001.ts".to_string(), 4, "deadbeef".to_string());
+        manifest.save(&dir).unwrap();
+
+        let loaded = Manifest::load_or_default(&dir, "https://example.com/master.m3u8".to_string());
+        assert_eq!(loaded.source, manifest.source);
+        assert_eq!(loaded.variant_uri, manifest.variant_uri);
+        assert!(loaded.segments.contains_key("segment-001.ts"));
+    }
+
+    #[test]
+    fn load_or_default_falls_back_when_no_manifest_exists() {
+        let dir = test_dir("missing_manifest");
+        let manifest = Manifest::load_or_default(&dir, "https://example.com/master.m3u8".to_string());
+        assert_eq!(manifest.source, "https://example.com/master.m3u8");
+        assert!(manifest.segments.is_empty());
+    }
+}
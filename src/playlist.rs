@@ -6,80 +6,287 @@ use url::Url;
 pub struct Playlist {
     pub content: String,
     pub segments: Vec<String>,
+    pub segment_keys: Vec<Option<KeyTag>>,
+    pub segment_positions: Vec<Option<u64>>,
     pub url: Url,
 }
 
+#[derive(Debug, Clone)]
+pub struct KeyTag {
+    pub method: String,
+    pub uri: Option<String>,
+    pub iv: Option<[u8; 16]>,
+}
+
+impl KeyTag {
+    pub fn is_decryptable(&self) -> bool {
+        self.method == "AES-128" && self.uri.is_some()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaSegment {
+    pub extinf: Option<String>,
+    pub uri: String,
+    pub key: Option<KeyTag>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VariantStream {
+    pub uri: String,
+    pub bandwidth: Option<u64>,
+    pub resolution: Option<(u32, u32)>,
+    pub codecs: Option<String>,
+    pub audio_group: Option<String>,
+    pub subtitles_group: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaRendition {
+    pub media_type: String,
+    pub group_id: String,
+    pub uri: Option<String>,
+}
+
 impl Playlist {
     pub fn parse(content: &str, base_url: &Url) -> Result<Self> {
         let mut segments = Vec::new();
+        let mut segment_keys = Vec::new();
+        let mut segment_positions = Vec::new();
         let uri_regex = Regex::new(r#"URI="([^"]+)""#)?;
-        
+        let mut current_key: Option<KeyTag> = None;
+        let mut position: u64 = 0;
+
         for line in content.lines() {
             let line = line.trim();
-            
-            if line.starts_with("#EXT-X-MAP:") {
+
+            if line.starts_with("#EXT-X-KEY:") {
+                current_key = Self::parse_key_tag(line);
+            } else if line.starts_with("#EXT-X-MAP:") {
                 if let Some(caps) = uri_regex.captures(line) {
                     if let Some(uri_match) = caps.get(1) {
                         segments.push(uri_match.as_str().to_string());
+                        segment_keys.push(None);
+                        segment_positions.push(None);
                     }
                 }
             } else if !line.starts_with('#') && !line.is_empty() {
                 segments.push(line.to_string());
+                segment_keys.push(current_key.clone());
+                segment_positions.push(Some(position));
+                position += 1;
             }
         }
 
         Ok(Playlist {
             content: content.to_string(),
             segments,
+            segment_keys,
+            segment_positions,
             url: base_url.clone(),
         })
     }
 
+    fn parse_key_tag(line: &str) -> Option<KeyTag> {
+        let method_regex = Regex::new(r"METHOD=([A-Za-z0-9-]+)").unwrap();
+        let uri_regex = Regex::new(r#"URI="([^"]+)""#).unwrap();
+        let iv_regex = Regex::new(r"IV=0[xX]([0-9A-Fa-f]+)").unwrap();
+
+        let method = method_regex.captures(line)?.get(1)?.as_str().to_string();
+        if method == "NONE" {
+            return None;
+        }
+
+        let uri = uri_regex.captures(line).and_then(|caps| caps.get(1)).map(|m| m.as_str().to_string());
+        let iv = iv_regex.captures(line)
+            .and_then(|caps| caps.get(1))
+            .and_then(|m| Self::parse_iv_hex(m.as_str()));
+
+        Some(KeyTag { method, uri, iv })
+    }
+
+    fn parse_iv_hex(hex: &str) -> Option<[u8; 16]> {
+        if hex.len() != 32 {
+            return None;
+        }
+        let mut iv = [0u8; 16];
+        for (i, byte) in iv.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(iv)
+    }
+
+    pub fn derive_iv_from_sequence(sequence: u64) -> [u8; 16] {
+        let mut iv = [0u8; 16];
+        iv[8..].copy_from_slice(&sequence.to_be_bytes());
+        iv
+    }
+
+    pub fn has_endlist(content: &str) -> bool {
+        content.lines().any(|line| line.trim().starts_with("#EXT-X-ENDLIST"))
+    }
+
+    pub fn target_duration(content: &str) -> Option<u64> {
+        content.lines()
+            .find_map(|line| line.trim().strip_prefix("#EXT-X-TARGETDURATION:"))
+            .and_then(|value| value.trim().parse().ok())
+    }
+
+    pub fn media_sequence(content: &str) -> u64 {
+        content.lines()
+            .find_map(|line| line.trim().strip_prefix("#EXT-X-MEDIA-SEQUENCE:"))
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    pub fn media_segments(content: &str) -> Vec<MediaSegment> {
+        let mut segments = Vec::new();
+        let mut pending_extinf: Option<String> = None;
+        let mut current_key: Option<KeyTag> = None;
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.starts_with("#EXTINF:") {
+                pending_extinf = Some(line.to_string());
+            } else if line.starts_with("#EXT-X-KEY:") {
+                current_key = Self::parse_key_tag(line);
+            } else if !line.starts_with('#') && !line.is_empty() {
+                segments.push(MediaSegment {
+                    extinf: pending_extinf.take(),
+                    uri: line.to_string(),
+                    key: current_key.clone(),
+                });
+            }
+        }
+
+        segments
+    }
+
     pub fn is_master_playlist(content: &str) -> bool {
         content.contains("#EXT-X-STREAM-INF") || 
         content.contains("#EXT-X-MEDIA") || 
         content.contains("#EXT-X-I-FRAME-STREAM-INF")
     }
 
-    pub fn extract_all_playlists(content: &str) -> Vec<String> {
-        let mut playlists = Vec::new();
+    pub fn extract_variants(content: &str) -> Vec<VariantStream> {
+        let bandwidth_regex = Regex::new(r"BANDWIDTH=(\d+)").unwrap();
+        let resolution_regex = Regex::new(r"RESOLUTION=(\d+)x(\d+)").unwrap();
+        let codecs_regex = Regex::new(r#"CODECS="([^"]+)""#).unwrap();
+        let audio_regex = Regex::new(r#"AUDIO="([^"]+)""#).unwrap();
+        let subtitles_regex = Regex::new(r#"SUBTITLES="([^"]+)""#).unwrap();
+
         let lines: Vec<&str> = content.lines().collect();
-        let uri_regex = Regex::new(r#"URI="([^"]+)""#).unwrap();
-        
+        let mut variants = Vec::new();
+
         for (i, line) in lines.iter().enumerate() {
             let line = line.trim();
-            
+            if !line.starts_with("#EXT-X-STREAM-INF") {
+                continue;
+            }
+
+            let uri = match lines.get(i + 1) {
+                Some(next) if !next.trim().starts_with('#') && !next.trim().is_empty() => next.trim().to_string(),
+                _ => continue,
+            };
+
+            variants.push(VariantStream {
+                uri,
+                bandwidth: bandwidth_regex.captures(line)
+                    .and_then(|caps| caps.get(1))
+                    .and_then(|m| m.as_str().parse().ok()),
+                resolution: resolution_regex.captures(line)
+                    .and_then(|caps| Some((caps.get(1)?.as_str().parse().ok()?, caps.get(2)?.as_str().parse().ok()?))),
+                codecs: codecs_regex.captures(line).and_then(|caps| caps.get(1)).map(|m| m.as_str().to_string()),
+                audio_group: audio_regex.captures(line).and_then(|caps| caps.get(1)).map(|m| m.as_str().to_string()),
+                subtitles_group: subtitles_regex.captures(line).and_then(|caps| caps.get(1)).map(|m| m.as_str().to_string()),
+            });
+        }
+
+        variants
+    }
+
+    pub fn extract_media_renditions(content: &str) -> Vec<MediaRendition> {
+        let type_regex = Regex::new(r"TYPE=([A-Z-]+)").unwrap();
+        let group_id_regex = Regex::new(r#"GROUP-ID="([^"]+)""#).unwrap();
+        let uri_regex = Regex::new(r#"URI="([^"]+)""#).unwrap();
+
+        content.lines()
+            .map(|line| line.trim())
+            .filter(|line| line.starts_with("#EXT-X-MEDIA:"))
+            .filter_map(|line| {
+                let media_type = type_regex.captures(line)?.get(1)?.as_str().to_string();
+                let group_id = group_id_regex.captures(line)?.get(1)?.as_str().to_string();
+                let uri = uri_regex.captures(line).and_then(|caps| caps.get(1)).map(|m| m.as_str().to_string());
+
+                Some(MediaRendition { media_type, group_id, uri })
+            })
+            .collect()
+    }
+
+    pub fn rewrite_master_single_variant(
+        master_content: &str,
+        variant: &VariantStream,
+        variant_filename: &str,
+        rendition_filenames: &std::collections::HashMap<String, String>,
+    ) -> String {
+        let keep_groups: Vec<&str> = [variant.audio_group.as_deref(), variant.subtitles_group.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        let group_id_regex = Regex::new(r#"GROUP-ID="([^"]+)""#).unwrap();
+        let uri_regex = Regex::new(r#"URI="([^"]+)""#).unwrap();
+
+        let lines: Vec<&str> = master_content.lines().collect();
+        let mut output: Vec<String> = Vec::new();
+        let mut i = 0;
+
+        while i < lines.len() {
+            let line = lines[i].trim();
+
             if line.starts_with("#EXT-X-STREAM-INF") {
-                if let Some(next_line) = lines.get(i + 1) {
-                    let next_line = next_line.trim();
-                    if !next_line.starts_with('#') && !next_line.is_empty() {
-                        playlists.push(next_line.to_string());
-                    }
+                if lines.get(i + 1).map(|next| next.trim()) == Some(variant.uri.as_str()) {
+                    output.push(line.to_string());
+                    output.push(variant_filename.to_string());
                 }
+                i += 2;
+                continue;
             }
-            else if line.starts_with("#EXT-X-MEDIA") {
-                if let Some(caps) = uri_regex.captures(line) {
-                    if let Some(uri_match) = caps.get(1) {
-                        playlists.push(uri_match.as_str().to_string());
-                    }
-                }
+
+            if line.starts_with("#EXT-X-I-FRAME-STREAM-INF") {
+                i += 1;
+                continue;
             }
-            else if line.starts_with("#EXT-X-I-FRAME-STREAM-INF") {
-                if let Some(caps) = uri_regex.captures(line) {
-                    if let Some(uri_match) = caps.get(1) {
-                        playlists.push(uri_match.as_str().to_string());
-                    }
+
+            if line.starts_with("#EXT-X-MEDIA") {
+                let in_selected_group = group_id_regex.captures(line)
+                    .and_then(|caps| caps.get(1))
+                    .map(|m| keep_groups.contains(&m.as_str()))
+                    .unwrap_or(false);
+
+                if in_selected_group {
+                    let rewritten = uri_regex.replace(line, |caps: &regex::Captures| {
+                        match rendition_filenames.get(&caps[1]) {
+                            Some(filename) => format!(r#"URI="{}""#, filename),
+                            None => caps[0].to_string(),
+                        }
+                    });
+                    output.push(rewritten.to_string());
                 }
+                i += 1;
+                continue;
             }
+
+            output.push(line.to_string());
+            i += 1;
         }
-        
-        playlists
+
+        output.join("\n")
     }
 
-    pub fn rewrite_content(&self) -> String {
+    pub fn rewrite_content(&self, decrypt: bool) -> String {
         let mut content = self.content.clone();
         let uri_regex = Regex::new(r#"URI="([^"]+)""#).unwrap();
-        
+
         content = uri_regex.replace_all(&content, |caps: &regex::Captures| {
             let original_uri = &caps[1];
             if let Ok(url) = Url::parse(original_uri) {
@@ -91,10 +298,18 @@ impl Playlist {
                 caps[0].to_string()
             }
         }).to_string();
-        
+
         let mut lines: Vec<String> = Vec::new();
         for line in content.lines() {
             let line = line.trim();
+            if decrypt && line.starts_with("#EXT-X-KEY:") {
+                let decrypted = Self::parse_key_tag(line)
+                    .map(|tag| tag.is_decryptable())
+                    .unwrap_or(false);
+                if decrypted {
+                    continue;
+                }
+            }
             if !line.starts_with('#') && !line.is_empty() {
                 if let Ok(_) = Url::parse(line) {
                     if let Ok(url) = Url::parse(line) {
@@ -115,4 +330,55 @@ impl Playlist {
         
         lines.join("\n")
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_iv_as_sequence_number_in_the_last_8_bytes() {
+        let iv = Playlist::derive_iv_from_sequence(5);
+
+        let mut expected = [0u8; 16];
+        expected[15] = 5;
+        assert_eq!(iv, expected);
+    }
+
+    #[test]
+    fn derives_iv_as_big_endian() {
+        let sequence: u64 = 0x0102030405060708;
+        let iv = Playlist::derive_iv_from_sequence(sequence);
+
+        let mut expected = [0u8; 16];
+        expected[8..].copy_from_slice(&sequence.to_be_bytes());
+        assert_eq!(iv, expected);
+    }
+
+    #[test]
+    fn derives_iv_zero_for_sequence_zero() {
+        assert_eq!(Playlist::derive_iv_from_sequence(0), [0u8; 16]);
+    }
+
+    #[test]
+    fn rewrite_content_keeps_key_tag_for_methods_it_cannot_decrypt() {
+        let url = Url::parse("https://example.com/media.m3u8").unwrap();
+        let content = "#EXTM3U\n#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"key.bin\"\n#EXTINF:6,\nhttps://example.com/segment-001.ts\n";
+        let playlist = Playlist::parse(content, &url).unwrap();
+
+        let rewritten = playlist.rewrite_content(true);
+
+        assert!(rewritten.contains("#EXT-X-KEY:METHOD=SAMPLE-AES"));
+    }
+
+    #[test]
+    fn rewrite_content_drops_key_tag_for_decrypted_aes128() {
+        let url = Url::parse("https://example.com/media.m3u8").unwrap();
+        let content = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n#EXTINF:6,\nhttps://example.com/segment-001.ts\n";
+        let playlist = Playlist::parse(content, &url).unwrap();
+
+        let rewritten = playlist.rewrite_content(true);
+
+        assert!(!rewritten.contains("#EXT-X-KEY"));
+    }
 }
\ No newline at end of file